@@ -11,8 +11,14 @@ fn main() {
         .whitelisted_function("getcontext")
         .whitelisted_function("makecontext")
         .whitelisted_function("swapcontext")
-        .whitelisted_function("valloc")
-        .whitelisted_function("free")
+        .whitelisted_function("mmap")
+        .whitelisted_function("munmap")
+        .whitelisted_function("mprotect")
+        .whitelisted_var("PROT_NONE")
+        .whitelisted_var("PROT_READ")
+        .whitelisted_var("PROT_WRITE")
+        .whitelisted_var("MAP_PRIVATE")
+        .whitelisted_var("MAP_ANONYMOUS")
         .generate_inline_functions(true)
         .generate()
         .expect("generate bindings");
@@ -0,0 +1,704 @@
+//! An alternative backend to `ucontext` that switches contexts with a hand-written register
+//! swap instead of POSIX `swapcontext()`. `swapcontext()` additionally saves and restores the
+//! signal mask on every call (a `sigprocmask` syscall), which dwarfs the cost of the handful of
+//! register moves a context switch actually requires; this backend skips that syscall entirely.
+//!
+//! The technique (and the `terminate` fast path below) follows the approach used by
+//! `boost.context`'s `fcontext_t` and by `corosensei`: save only the callee-saved registers
+//! (`rbx`, `rbp`, `r12`-`r15`) on the current stack, swap `rsp`, and `ret` into the other side.
+//! x86-64 only.
+
+use std::mem;
+use std::marker::PhantomData;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::arch::naked_asm;
+
+use crate::stack::Stack;
+use crate::pool::global_pool;
+
+/// Save the callee-saved registers on the current stack, record the resulting `rsp` through
+/// `out_rsp`, then load `new_rsp` and `ret` into whatever previously suspended there.
+///
+/// `out_rsp` and `new_rsp` are addresses at which to resume each side later, not data to
+/// interpret — this is exactly the same contract as `swapcontext()`'s two `ucontext_t*` args,
+/// just a single word instead of a whole saved-signal-mask-and-registers structure.
+#[unsafe(naked)]
+unsafe extern "system" fn swap_context(out_rsp: *mut usize, new_rsp: usize) {
+    naked_asm!(
+        "push rbx",
+        "push rbp",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov [rdi], rsp",
+        "mov rsp, rsi",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbp",
+        "pop rbx",
+        "ret",
+    );
+}
+
+/// Like `swap_context`, but for a coroutine that is terminating: there's no reason to spend
+/// instructions saving registers nobody will ever restore, so this just loads `new_rsp` and
+/// returns into it. This is the `terminate` fast path.
+#[unsafe(naked)]
+unsafe extern "system" fn set_context(new_rsp: usize) -> ! {
+    naked_asm!(
+        "mov rsp, rdi",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbp",
+        "pop rbx",
+        "ret",
+    );
+}
+
+/// Lands here the first time a coroutine is resumed. `r15` holds the entrypoint function
+/// pointer and `r14`/`r13` its two arguments, restored by `swap_context`'s register pops from the
+/// fake initial frame `new_stack_rsp` built below; this just moves them into the System V
+/// argument registers and calls in.
+#[unsafe(naked)]
+unsafe extern "system" fn trampoline() -> ! {
+    naked_asm!(
+        "mov rdi, r14",
+        "mov rsi, r13",
+        "call r15",
+        "ud2",
+    );
+}
+
+/// Build the initial fake stack frame for a freshly allocated `stack`, such that resuming into
+/// the returned `rsp` for the first time lands in `trampoline`, which in turn calls
+/// `entrypoint(coro, f)`.
+unsafe fn new_stack_rsp(
+    stack: &Stack,
+    entrypoint: usize,
+    coro: usize,
+    f: usize,
+) -> usize {
+    // six callee-saved registers plus a return address, matching `swap_context`'s push/pop order
+    let frame = (stack.top() as usize) - 7 * mem::size_of::<usize>();
+    let words = frame as *mut usize;
+
+    *words.add(0) = entrypoint; // -> r15
+    *words.add(1) = coro;       // -> r14
+    *words.add(2) = f;          // -> r13
+    *words.add(3) = 0;          // -> r12
+    *words.add(4) = 0;          // -> rbp
+    *words.add(5) = 0;          // -> rbx
+    *words.add(6) = trampoline as usize; // return address `ret` jumps to
+
+    frame
+}
+
+/// The outcome of yielding into a coroutine: either it yielded a value back out and is still
+/// running, or it ran to completion and produced its final value.
+pub enum CoroutineResult<Yield, Return> {
+    /// The coroutine called `Coroutine::yield_back` with this value, and remains suspended.
+    Yielded(Yield),
+
+    /// The coroutine's body returned this value, and it is now terminated.
+    Complete(Return),
+}
+
+/// A value crossing a single context switch: a `Resume` going in, or a `Yield`/`Return` coming
+/// back out.
+enum Transfer<Resume, Yield, Return> {
+    Resume(Resume),
+    Yield(Yield),
+    Return(Return),
+
+    /// The coroutine's body panicked; this carries the payload to be re-raised in the caller.
+    Panic(Box<dyn Any + Send>),
+}
+
+/// A `Handle` is created for the outside of a coroutine. It contains the coroutine's saved
+/// register state and the coroutine's stack.
+///
+/// Dropping a `Handle` while its coroutine is suspended — whether mid-body, or having never run
+/// the body at all yet — forces the coroutine to unwind first, so that any locals the body left
+/// live across a `yield_back`, or the closure itself if it never even started, still run their
+/// destructors.
+pub struct Handle<'f, Resume, Yield, Return> {
+    // the coroutine's saved `rsp`, updated every time it suspends
+    rsp: usize,
+
+    // `None` only ever briefly, between `take()`ing it in `Drop` and the `Handle` itself going
+    // away; Rust doesn't see the stack get used, but it is referenced by `rsp`.
+    stack: Option<Stack>,
+
+    link: Rc<Cell<Link<Resume, Yield, Return>>>,
+
+    pd: PhantomData<&'f ()>
+}
+
+/// A `Coroutine` is created for the inside of a coroutine. It allows the coroutine to
+/// `yield_back()` a value to its caller and receive the next `Resume` value in exchange.
+pub struct Coroutine<'f, Resume, Yield, Return> {
+    link: Rc<Cell<Link<Resume, Yield, Return>>>,
+    pd: PhantomData<&'f ()>
+}
+
+/// `Link` encapsulates the communication of shared state between `Coroutine` and `Handle`.
+enum Link<Resume, Yield, Return> {
+    /// Indicates the coroutine is ready to be called
+    Ready,
+
+    /// Indicates the coroutine _was_ called, provides saved-`rsp` slots to which it should
+    /// return, and a slot through which the two sides exchange a `Transfer`.
+    Called {
+        left: *mut usize,
+        right: *const usize,
+        transfer: *mut Option<Transfer<Resume, Yield, Return>>,
+    },
+
+    /// Indicates the `Handle` was dropped while the coroutine was suspended: the coroutine
+    /// should force itself to unwind the next time it reaches `yield_back()`, and provides the
+    /// saved-`rsp` slot to which it should return once it has terminated.
+    Unwind {
+        left: *mut usize,
+        right: *const usize,
+    },
+
+    /// Indicates the coroutine is terminated and must not be called again.
+    Terminated
+}
+
+/// Private marker panic payload used to force a suspended coroutine to unwind. Nothing else
+/// should ever panic with this payload, so the entrypoint re-raises any payload that isn't
+/// exactly this marker rather than risk swallowing a real panic.
+struct ForcedUnwind;
+
+// `Link`'s fields are all raw pointers, so it's `Copy`/`Clone` regardless of `Resume`, `Yield`,
+// and `Return`; derive(Copy, Clone) would incorrectly demand those bounds on the type parameters.
+impl<Resume, Yield, Return> Copy for Link<Resume, Yield, Return> {}
+impl<Resume, Yield, Return> Clone for Link<Resume, Yield, Return> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<'f, Resume, Yield, Return> Coroutine<'f, Resume, Yield, Return> {
+    /// Create a new `Coroutine`+`Handle` with the process-global default stack size (see
+    /// `Config`).
+    ///
+    /// The coroutine will call `f(&mut Coroutine)` when it starts.
+    pub fn new<F>(f: F) -> Handle<'f, Resume, Yield, Return>
+        where F: FnOnce(&mut Coroutine<'f, Resume, Yield, Return>) -> Return + 'f
+    {
+        Self::new_with_stack_size(f, crate::pool::default_stack_size())
+    }
+
+    /// Create a new `Coroutine`+`Handle` with a specific stack size.
+    ///
+    /// The coroutine will call `f(&mut Coroutine)` when it starts. The stack is taken from the
+    /// process-global `StackPool` rather than freshly allocated when one of the right size is
+    /// available.
+    pub fn new_with_stack_size<F>(f: F, stack_size: usize) -> Handle<'f, Resume, Yield, Return>
+        where F: FnOnce(&mut Coroutine<'f, Resume, Yield, Return>) -> Return + 'f
+    {
+        let stack = global_pool().get(stack_size);
+
+        // prepare a link, which we'll share between the Handle and the Coroutine
+        let link: Rc<Cell<Link<Resume, Yield, Return>>> = Rc::new(Cell::new(Link::Ready));
+
+        // prepare a Coroutine
+        let coro = Coroutine {
+            link: link.clone(),
+            pd: PhantomData
+        };
+
+        // wrap `f` and `coro` into Option<_>s, exactly as the `ucontext` backend does: they live
+        // on the constructor's stack, and the entrypoint moves them onto the coroutine's own
+        // stack before running anything of the caller's.
+        let mut coro: Option<Coroutine<Resume, Yield, Return>> = Some(coro);
+        let mut callback: Option<F> = Some(f);
+
+        unsafe extern "C" fn entrypoint<F, Resume, Yield, Return>(
+            coro: *mut Option<Coroutine<Resume, Yield, Return>>,
+            f: *mut Option<F>,
+        )
+            where F: FnOnce(&mut Coroutine<Resume, Yield, Return>) -> Return
+        {
+            // take the constructor's Coroutine
+            let mut coro: Coroutine<Resume, Yield, Return> =
+                mem::transmute::<*mut Option<Coroutine<Resume, Yield, Return>>, &mut Option<Coroutine<Resume, Yield, Return>>>(coro)
+                .take()
+                .unwrap();
+
+            // take the constructor's function
+            let f: F =
+                mem::transmute::<*mut Option<F>, &mut Option<F>>(f)
+                    .take()
+                    .unwrap();
+
+            // yield back, letting the constructor return. `bootstrap_yield_back` can itself
+            // panic with `ForcedUnwind` if the `Handle` is dropped before we're ever resumed
+            // again, so this also needs catching instead of letting it unwind straight through
+            // this `extern "C"` frame, which would be undefined behavior
+            match panic::catch_unwind(AssertUnwindSafe(|| coro.bootstrap_yield_back())) {
+                Ok(()) => {}
+                Err(ref payload) if payload.downcast_ref::<ForcedUnwind>().is_some() => {
+                    // the `Handle` was dropped before we ever ran `f`; we just unwound, so
+                    // there's no value to deliver back and no reason to call `f` at all.
+                    // `terminate_unwound` never returns, so drop `f` (and whatever it
+                    // captured) first instead of leaking it
+                    drop(f);
+                    coro.terminate_unwound();
+                    unreachable!();
+                }
+                Err(payload) => coro.terminate_panicked(payload),
+            }
+
+            // run the function, catching a panic instead of letting it unwind straight through
+            // this `extern "C"` frame, which would be undefined behavior
+            match panic::catch_unwind(AssertUnwindSafe(|| f(&mut coro))) {
+                Ok(ret) => coro.terminate(ret),
+                Err(ref payload) if payload.downcast_ref::<ForcedUnwind>().is_some() => {
+                    coro.terminate_unwound();
+                }
+                Err(payload) => coro.terminate_panicked(payload),
+            }
+        }
+
+        let entrypoint: unsafe extern "C" fn(*mut Option<Coroutine<Resume, Yield, Return>>, *mut Option<F>) = entrypoint;
+
+        let rsp = unsafe {
+            new_stack_rsp(
+                &stack,
+                entrypoint as usize,
+                &mut coro as *mut Option<Coroutine<Resume, Yield, Return>> as usize,
+                &mut callback as *mut Option<F> as usize,
+            )
+        };
+
+        // assemble all the outer bits into the handle
+        let mut handle = Handle {
+            rsp,
+            stack: Some(stack),
+            link,
+            pd: PhantomData,
+        };
+
+        // at this point, the Handle's `rsp` is ready to resume into `trampoline`, which will call
+        // entrypoint() with pointers to our local `coro` and `f` Option<_>s.
+        //
+        // yield into the coroutine, let it take the values out of `coro` and `f`, and yield back.
+        // no `Resume` value exists yet, so this goes through the untyped bootstrap handshake
+        // rather than `yield_in()`.
+        handle.bootstrap();
+
+        // the coroutine is now ready to invoke the user's function, and it shares no state except
+        // `link`.
+        //
+        // return to caller
+        handle
+    }
+
+    /// Yield `value` back to the caller, blocking until the caller calls `Handle::yield_in()`
+    /// again, and returning the `Resume` value it passed.
+    pub fn yield_back(&mut self, value: Yield) -> Resume {
+        let link = Cell::new(Link::Ready);
+        self.link.swap(&link);
+        match link.into_inner() {
+            Link::Called { left, right, transfer } => {
+                unsafe {
+                    *transfer = Some(Transfer::Yield(value));
+                    swap_context(left, *right);
+                }
+            }
+            _ => {
+                panic!("don't know where to yield back to");
+            }
+        }
+
+        // we've been resumed: whoever called `Handle::yield_in()` left our `Resume` value in the
+        // slot named by the `Link::Called` they just installed
+        match self.link.get() {
+            Link::Called { transfer, .. } => {
+                match unsafe { (*transfer).take() } {
+                    Some(Transfer::Resume(value)) => value,
+                    _ => panic!("coroutine resumed with no resume value"),
+                }
+            }
+            Link::Unwind { .. } => {
+                // the `Handle` was dropped while we were suspended here; force ourselves to
+                // unwind so that drop glue runs for everything live in this stack and above.
+                // `catch_unwind` in the entrypoint recognizes and swallows this exact payload.
+                panic::resume_unwind(Box::new(ForcedUnwind))
+            }
+            _ => panic!("coroutine resumed with nowhere to read from"),
+        }
+    }
+
+    /// Like `yield_back()`, but used only for the untyped handshake that lets the constructor
+    /// regain control once the entrypoint has taken ownership of its locals. No `Transfer` value
+    /// crosses this particular switch.
+    ///
+    /// A `Handle` can be dropped before ever calling `yield_in()`, while we're suspended right
+    /// here; like `yield_back()`, we check for `Link::Unwind` on resume and force an unwind in
+    /// that case, so the closure (and anything it captured) still gets dropped instead of leaked.
+    fn bootstrap_yield_back(&mut self) {
+        let link = Cell::new(Link::Ready);
+        self.link.swap(&link);
+        match link.into_inner() {
+            Link::Called { left, right, .. } => {
+                unsafe {
+                    swap_context(left, *right);
+                }
+            }
+            _ => {
+                panic!("don't know where to yield back to");
+            }
+        }
+
+        if let Link::Unwind { .. } = self.link.get() {
+            // `catch_unwind` around this call in the entrypoint recognizes and swallows this
+            // exact payload
+            panic::resume_unwind(Box::new(ForcedUnwind));
+        }
+    }
+
+    /// Terminate the coroutine, delivering `value` to whoever yields in next.
+    ///
+    /// # Safety
+    ///
+    /// Never returns.
+    unsafe fn terminate(&mut self, value: Return) {
+        let (transfer, right) = match self.link.get() {
+            Link::Called { transfer, right, .. } => (transfer, right),
+            _ => panic!("coroutine is complete but cannot return to caller"),
+        };
+
+        *transfer = Some(Transfer::Return(value));
+        self.link.set(Link::Terminated);
+
+        set_context(*right);
+    }
+
+    /// Terminate the coroutine because its body panicked, delivering the panic payload to
+    /// whoever yields in next so they can resume the unwind in their own stack.
+    ///
+    /// # Safety
+    ///
+    /// Never returns.
+    unsafe fn terminate_panicked(&mut self, payload: Box<dyn Any + Send>) {
+        let (transfer, right) = match self.link.get() {
+            Link::Called { transfer, right, .. } => (transfer, right),
+            _ => panic!("coroutine is complete but cannot return to caller"),
+        };
+
+        *transfer = Some(Transfer::Panic(payload));
+        self.link.set(Link::Terminated);
+
+        set_context(*right);
+    }
+
+    /// Terminate the coroutine after a forced unwind requested by `Handle::drop`. There's no
+    /// value to deliver: the `Handle` side isn't reading one, just waiting for termination.
+    ///
+    /// # Safety
+    ///
+    /// Never returns.
+    unsafe fn terminate_unwound(&mut self) {
+        let right = match self.link.get() {
+            Link::Unwind { right, .. } => right,
+            _ => panic!("coroutine is complete but cannot return to caller"),
+        };
+
+        self.link.set(Link::Terminated);
+
+        set_context(*right);
+    }
+}
+
+impl<'f, Resume, Yield, Return> Handle<'f, Resume, Yield, Return> {
+    /// Indicates whether or not the coroutine has terminated.
+    pub fn is_terminated(&self) -> bool {
+        match self.link.get() {
+            Link::Terminated => true,
+            _ => false,
+        }
+    }
+
+    /// Yield control into the coroutine, delivering `value`. This function blocks until either
+    /// the coroutine calls `Coroutine::yield_back()` or returns.
+    ///
+    /// The body receives `Resume` values only as the return of `yield_back()`, so the `value`
+    /// passed to the first call, which starts the body, is dropped without the body seeing it.
+    ///
+    /// Returns `Ok(CoroutineResult::Yielded(_))` or `Ok(CoroutineResult::Complete(_))` on success,
+    /// or `Err(())` if the coroutine could not be called because it has already terminated.
+    ///
+    /// If the coroutine's body panics, that panic is resumed here instead of returning, as if
+    /// the body had been invoked inline in this stack frame.
+    pub fn yield_in(&mut self, value: Resume) -> Result<CoroutineResult<Yield, Return>, ()> {
+        if self.is_terminated() {
+            return Err(());
+        }
+
+        let mut transfer: Option<Transfer<Resume, Yield, Return>> = Some(Transfer::Resume(value));
+
+        unsafe {
+            let mut here: usize = 0;
+            self.link.set(Link::Called{
+                left: &mut self.rsp as *mut usize,
+                right: &here as *const usize,
+                transfer: &mut transfer,
+            });
+
+            swap_context(&mut here as *mut usize, self.rsp);
+        }
+
+        // the coroutine wrote into our `transfer` slot before switching back
+        if self.is_terminated() {
+            match transfer.take() {
+                Some(Transfer::Return(value)) => Ok(CoroutineResult::Complete(value)),
+                // re-raise the coroutine's panic here, in the caller's stack, as if the body had
+                // run inline
+                Some(Transfer::Panic(payload)) => panic::resume_unwind(payload),
+                _ => panic!("coroutine terminated with no return value"),
+            }
+        } else {
+            match transfer.take() {
+                Some(Transfer::Yield(value)) => Ok(CoroutineResult::Yielded(value)),
+                _ => panic!("coroutine yielded with no value"),
+            }
+        }
+    }
+
+    /// Like `yield_in()`, but used only for the untyped handshake performed once by
+    /// `Coroutine::new_with_stack_size()`. No `Transfer` value crosses this particular switch.
+    fn bootstrap(&mut self) {
+        unsafe {
+            let mut here: usize = 0;
+            self.link.set(Link::Called{
+                left: &mut self.rsp as *mut usize,
+                right: &here as *const usize,
+                transfer: std::ptr::null_mut(),
+            });
+
+            swap_context(&mut here as *mut usize, self.rsp);
+        }
+    }
+
+    /// Force a suspended coroutine to unwind its stack, running drop glue for every local the
+    /// body left live across a `yield_back`, then block until it reaches `Link::Terminated`.
+    fn force_unwind(&mut self) {
+        unsafe {
+            let mut here: usize = 0;
+            self.link.set(Link::Unwind {
+                left: &mut self.rsp as *mut usize,
+                right: &here as *const usize,
+            });
+
+            swap_context(&mut here as *mut usize, self.rsp);
+        }
+
+        debug_assert!(self.is_terminated(), "coroutine did not terminate after a forced unwind");
+    }
+}
+
+impl<'f, Resume, Yield, Return> Drop for Handle<'f, Resume, Yield, Return> {
+    fn drop(&mut self) {
+        if !self.is_terminated() {
+            // the coroutine is suspended somewhere between having been handed its closure (at
+            // the latest, inside `bootstrap_yield_back`) and returning, with locals potentially
+            // live across its last `yield_back`; force it to unwind so their destructors run
+            // instead of leaking them
+            self.force_unwind();
+        }
+
+        // only a terminated coroutine's stack holds no live state, so only it is safe to recycle
+        if self.is_terminated() {
+            if let Some(stack) = self.stack.take() {
+                global_pool().put(stack);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{Ordering,AtomicUsize};
+
+    #[test]
+    fn test_create_destroy() {
+        let seq = AtomicUsize::new(0);
+
+        {
+            // create a coroutine
+            let mut coro = Coroutine::new(|_: &mut Coroutine<(), (), ()>| {
+                seq.store(1, Ordering::Release);
+            });
+
+            // don't ever actually call it
+            if false {
+                coro.yield_in(()).unwrap();
+            }
+
+            // dropping without calling should not be an error
+        }
+
+        // ...and the value should remain unchanged
+        assert_eq!(seq.load(Ordering::Acquire), 0);
+    }
+
+    #[test]
+    fn test_asm_switch() {
+        let seq = AtomicUsize::new(0);
+
+        let mut coro = Coroutine::new(|coro: &mut Coroutine<(), (), ()>| {
+            // in coroutine (1 => 2)
+            assert_eq!(seq.load(Ordering::Acquire), 1);
+            seq.store(2, Ordering::Release);
+
+            coro.yield_back(());
+
+            // back in coroutine (3 => 4)
+            assert_eq!(seq.load(Ordering::Acquire), 3);
+            seq.store(4, Ordering::Release);
+        });
+
+        // sequence of events:
+
+        // nothing (0 => 1)
+        assert_eq!(seq.load(Ordering::Acquire), 0);
+        assert_eq!(coro.is_terminated(), false);
+        seq.store(1, Ordering::Release);
+
+        coro.yield_in(()).unwrap();
+
+        // back from coroutine (2 => 3)
+        assert_eq!(seq.load(Ordering::Acquire), 2);
+        assert_eq!(coro.is_terminated(), false);
+        seq.store(3, Ordering::Release);
+
+        coro.yield_in(()).unwrap();
+
+        // done (4!)
+        assert_eq!(seq.load(Ordering::Acquire), 4);
+        assert_eq!(coro.is_terminated(), true);
+    }
+
+    #[test]
+    fn test_typed_values() {
+        let mut coro = Coroutine::new(|coro: &mut Coroutine<i32, &'static str, bool>| {
+            let a = coro.yield_back("first");
+            let b = coro.yield_back("second");
+            a + b == 3
+        });
+
+        // the first `yield_in` only starts the body, which has nowhere to receive its value;
+        // each `yield_back` returns the value passed to the `yield_in` after it
+        match coro.yield_in(0).unwrap() {
+            CoroutineResult::Yielded(y) => assert_eq!(y, "first"),
+            CoroutineResult::Complete(_) => panic!("expected a yield"),
+        }
+
+        match coro.yield_in(1).unwrap() {
+            CoroutineResult::Yielded(y) => assert_eq!(y, "second"),
+            CoroutineResult::Complete(_) => panic!("expected a yield"),
+        }
+
+        match coro.yield_in(2).unwrap() {
+            CoroutineResult::Yielded(_) => panic!("expected completion"),
+            CoroutineResult::Complete(r) => assert!(r),
+        }
+
+        assert!(coro.is_terminated());
+    }
+
+    #[test]
+    fn test_panic_propagates() {
+        let mut coro = Coroutine::new(|_: &mut Coroutine<(), (), ()>| -> () {
+            panic!("boom");
+        });
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| coro.yield_in(())));
+        assert!(result.is_err());
+        assert!(coro.is_terminated());
+    }
+
+    #[test]
+    fn test_stack_reuse() {
+        // a distinctive size so this test doesn't fight other tests over pooled stacks
+        let stack_size = 321 * 4096;
+
+        for _ in 0..4 {
+            let mut coro = Coroutine::new_with_stack_size(
+                |_: &mut Coroutine<(), (), ()>| {},
+                stack_size,
+            );
+            coro.yield_in(()).unwrap();
+            assert!(coro.is_terminated());
+            // dropping here returns the stack to the pool for the next iteration to reuse
+        }
+    }
+
+    #[test]
+    fn test_drop_unwinds_suspended_coroutine() {
+        struct DropFlag<'a>(&'a AtomicUsize);
+        impl<'a> Drop for DropFlag<'a> {
+            fn drop(&mut self) {
+                self.0.store(1, Ordering::Release);
+            }
+        }
+
+        let dropped = AtomicUsize::new(0);
+
+        {
+            let mut coro = Coroutine::new(|coro: &mut Coroutine<(), (), ()>| {
+                let _guard = DropFlag(&dropped);
+                coro.yield_back(());
+                unreachable!("forced unwind should never resume the body");
+            });
+
+            coro.yield_in(()).unwrap();
+            assert!(!coro.is_terminated());
+            assert_eq!(dropped.load(Ordering::Acquire), 0);
+
+            // dropping while suspended mid-body should run `DropFlag`'s destructor
+        }
+
+        assert_eq!(dropped.load(Ordering::Acquire), 1);
+    }
+
+    #[test]
+    fn test_drop_unwinds_never_started_coroutine() {
+        struct DropFlag<'a>(&'a AtomicUsize);
+        impl<'a> Drop for DropFlag<'a> {
+            fn drop(&mut self) {
+                self.0.store(1, Ordering::Release);
+            }
+        }
+
+        let dropped = AtomicUsize::new(0);
+
+        {
+            let guard = DropFlag(&dropped);
+
+            let coro = Coroutine::new(move |_: &mut Coroutine<(), (), ()>| {
+                let _guard = guard;
+                unreachable!("forced unwind should never resume the body");
+            });
+
+            assert!(!coro.is_terminated());
+            assert_eq!(dropped.load(Ordering::Acquire), 0);
+
+            // dropping here, without ever calling `yield_in`, should still unwind the closure
+            // the coroutine has already been handed, running `DropFlag`'s destructor
+        }
+
+        assert_eq!(dropped.load(Ordering::Acquire), 1);
+    }
+}
@@ -1,24 +1,42 @@
+// Bindgen-created bindings, shared by every backend (the stack is `mmap`/`mprotect`-based
+// regardless of how context switches happen).
+mod sys;
+
+mod stack;
+mod pool;
+
+pub use pool::{Config, ConfigBuilder};
+
+// Two interchangeable implementations of `Handle`/`Coroutine`, selected by Cargo feature:
+// `ucontext` switches contexts via POSIX `swapcontext()`, `asm` via a hand-written register
+// swap. `ucontext` wins if both are enabled, since it's the portable default.
+#[cfg(feature = "ucontext")]
 mod ucontext;
+#[cfg(feature = "ucontext")]
+pub use ucontext::{Handle,Coroutine,CoroutineResult};
 
-pub use ucontext::{Handle,Coroutine};
+#[cfg(all(feature = "asm", not(feature = "ucontext")))]
+mod asm;
+#[cfg(all(feature = "asm", not(feature = "ucontext")))]
+pub use asm::{Handle,Coroutine,CoroutineResult};
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
     fn example() -> Result<(),()> {
-        let mut handle = Coroutine::new(|coro: &mut Coroutine| {
+        let mut handle = Coroutine::new(|coro: &mut Coroutine<(), (), ()>| {
             println!("2: in coroutine");
-            coro.yield_back();
+            coro.yield_back(());
             println!("4: in coroutine");
 
         });
         assert!(!handle.is_terminated());
 
         println!("1: in caller");
-        handle.yield_in()?;
+        handle.yield_in(())?;
         println!("3: in caller");
-        handle.yield_in()?;
+        handle.yield_in(())?;
         println!("5: terminated!");
 
         assert!(handle.is_terminated());
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, Once};
+
+use crate::stack::{round_up_to_page, Stack, PAGE_SIZE};
+
+/// Process-wide tuning knobs for stack allocation.
+#[derive(Copy, Clone, Debug)]
+pub struct Config {
+    /// Stack size used by `Coroutine::new`.
+    pub stack_size: usize,
+
+    /// Maximum number of freed stacks kept around per size, ready for reuse by
+    /// `Coroutine::new_with_stack_size`. Stacks beyond this are unmapped instead of pooled.
+    pub pool_capacity: usize,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            stack_size: 512 * 1024,
+            pool_capacity: 64,
+        }
+    }
+}
+
+impl Config {
+    /// Start building a `Config`, overriding fields from `Config::default()`.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder(Config::default())
+    }
+}
+
+/// Builds a `Config`, optionally installing it as the process-global default.
+pub struct ConfigBuilder(Config);
+
+impl ConfigBuilder {
+    /// Override the stack size used by `Coroutine::new`.
+    pub fn stack_size(mut self, stack_size: usize) -> Self {
+        self.0.stack_size = stack_size;
+        self
+    }
+
+    /// Override how many freed stacks of each size are kept around for reuse.
+    pub fn pool_capacity(mut self, pool_capacity: usize) -> Self {
+        self.0.pool_capacity = pool_capacity;
+        self
+    }
+
+    /// Finish building without installing it as the global default.
+    pub fn build(self) -> Config {
+        self.0
+    }
+
+    /// Install this `Config` as the process-global default.
+    ///
+    /// `stack_size` takes effect immediately for every subsequent `Coroutine::new`. `pool_capacity`
+    /// only matters the first time it's read, when the global stack pool is lazily initialized;
+    /// call `install` before creating the first coroutine if you want to override it, since later
+    /// calls have no effect on an already-initialized pool.
+    pub fn install(self) {
+        set_global_config(self.0);
+    }
+}
+
+/// A pool of freed stacks, keyed by size, so that spinning up many short-lived coroutines of the
+/// same stack size doesn't pay for a fresh `mmap`/`mprotect` every time.
+pub struct StackPool {
+    capacity: usize,
+    free: Mutex<HashMap<usize, Vec<Stack>>>,
+}
+
+impl StackPool {
+    fn new(capacity: usize) -> StackPool {
+        StackPool {
+            capacity,
+            free: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a stack of at least `size` bytes, reusing a pooled one if one's available.
+    pub(crate) fn get(&self, size: usize) -> Stack {
+        let size = round_up_to_page(size);
+
+        let pooled = self.free.lock().unwrap()
+            .get_mut(&size)
+            .and_then(Vec::pop);
+
+        pooled.unwrap_or_else(|| Stack::new_protected(size))
+    }
+
+    /// Return a freed stack to the pool, up to `capacity` per size; the rest are dropped (and
+    /// thus unmapped) immediately.
+    pub(crate) fn put(&self, stack: Stack) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut free = self.free.lock().unwrap();
+        let bucket = free.entry(stack.size).or_insert_with(Vec::new);
+        if bucket.len() < self.capacity {
+            bucket.push(stack);
+        }
+    }
+}
+
+// `stack_size` is read fresh by every `Coroutine::new`, so it lives in an atomic that `install`
+// can update at any time without synchronization. `pool_capacity` only matters once, the first
+// time `global_pool()` runs its `Once`; storing it in an atomic rather than a `static mut` means
+// a racing `install()` can only win or lose that one read, never tear it or violate aliasing.
+static STACK_SIZE: AtomicUsize = AtomicUsize::new(512 * 1024);
+static POOL_CAPACITY: AtomicUsize = AtomicUsize::new(64);
+
+static INIT: Once = Once::new();
+static mut POOL: Option<StackPool> = None;
+
+fn set_global_config(config: Config) {
+    STACK_SIZE.store(config.stack_size, Ordering::Relaxed);
+    POOL_CAPACITY.store(config.pool_capacity, Ordering::Relaxed);
+}
+
+/// The stack pool backing `Coroutine::new`/`Coroutine::new_with_stack_size`, initialized on
+/// first use from the installed (or default) `Config`.
+pub(crate) fn global_pool() -> &'static StackPool {
+    unsafe {
+        INIT.call_once(|| {
+            POOL = Some(StackPool::new(POOL_CAPACITY.load(Ordering::Relaxed)));
+        });
+        POOL.as_ref().unwrap()
+    }
+}
+
+pub(crate) fn default_stack_size() -> usize {
+    STACK_SIZE.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_builder_overrides_defaults() {
+        let config = Config::builder().stack_size(999).pool_capacity(3).build();
+        assert_eq!(config.stack_size, 999);
+        assert_eq!(config.pool_capacity, 3);
+    }
+
+    #[test]
+    fn test_install_changes_default_stack_size() {
+        let previous = default_stack_size();
+
+        Config::builder().stack_size(previous + PAGE_SIZE).install();
+        assert_eq!(default_stack_size(), previous + PAGE_SIZE);
+
+        // restore, since `STACK_SIZE` is process-global and other tests rely on the default
+        Config::builder().stack_size(previous).install();
+    }
+
+    #[test]
+    fn test_stack_pool_respects_capacity() {
+        // exercise a standalone `StackPool` rather than the process-global one, since the
+        // global pool is a one-shot `Once` shared with every other test in the process
+        let pool = StackPool::new(1);
+        let size = round_up_to_page(1);
+
+        let a = pool.get(size);
+        let b = pool.get(size);
+        pool.put(a);
+        pool.put(b);
+
+        // only `capacity` (1) of the two returned stacks should have been kept
+        assert_eq!(pool.free.lock().unwrap().get(&size).unwrap().len(), 1);
+    }
+}
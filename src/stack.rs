@@ -0,0 +1,78 @@
+use std::os::raw::c_void;
+use std::ptr;
+
+use crate::sys::{mmap, munmap, mprotect};
+use crate::sys::{PROT_NONE, PROT_READ, PROT_WRITE, MAP_PRIVATE, MAP_ANONYMOUS};
+
+// The page size assumed for guard-page placement. This crate targets 4 KiB-page platforms and
+// never queries the real page size; on a platform with larger pages, `mprotect` rounds the guard
+// length up to a whole page, so the protected region reaches into the low end of the usable stack.
+pub(crate) const PAGE_SIZE: usize = 4096;
+
+pub(crate) fn round_up_to_page(size: usize) -> usize {
+    (size + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE
+}
+
+// `Stack` is an `mmap`-backed region of memory suitable for use as a coroutine's stack, with a
+// `PROT_NONE` guard page immediately below the usable region.
+//
+// A coroutine that overflows its stack faults against the guard page instead of silently
+// corrupting whatever memory happened to follow it. It's shared by every backend: only the code
+// that points a context at the stack (`ucontext_t.uc_stack`, or an initial `rsp`) differs.
+pub(crate) struct Stack {
+    // the whole mapping, guard page included; `Drop` unmaps all of it
+    mapping: *mut c_void,
+    mapping_size: usize,
+
+    // the usable region, handed to the backend's context
+    pub(crate) ptr: *mut c_void,
+    pub(crate) size: usize,
+}
+
+impl Stack {
+    /// Allocate a stack of at least `size` bytes with a guard page immediately below it.
+    ///
+    /// `size` is rounded up to a page multiple.
+    pub(crate) fn new_protected(size: usize) -> Stack {
+        let size = round_up_to_page(size);
+        let mapping_size = size + PAGE_SIZE;
+
+        let mapping = unsafe {
+            mmap(
+                ptr::null_mut(),
+                mapping_size,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+
+        if mapping == usize::max_value() as *mut c_void {
+            panic!("mmap() failed");
+        }
+
+        // the stack grows downward from the top of the usable region toward the guard page, so
+        // the guard page goes at the low end of the mapping
+        if unsafe { mprotect(mapping, PAGE_SIZE, PROT_NONE) } != 0 {
+            panic!("mprotect() failed");
+        }
+
+        let ptr = unsafe { (mapping as *mut u8).add(PAGE_SIZE) as *mut c_void };
+
+        Stack { mapping, mapping_size, ptr, size }
+    }
+
+    /// The address one past the end of the usable region, i.e. where a stack pointer starts.
+    pub(crate) fn top(&self) -> *mut u8 {
+        unsafe { (self.ptr as *mut u8).add(self.size) }
+    }
+}
+
+impl Drop for Stack {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.mapping, self.mapping_size);
+        }
+    }
+}
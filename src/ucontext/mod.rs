@@ -2,102 +2,123 @@ use std::mem;
 use std::marker::PhantomData;
 use std::cell::Cell;
 use std::rc::Rc;
-use std::os::raw::c_void;
-
-// Include bindgen-created bindings, but pull in only the bits we need
-mod sys;
-use self::sys::{ucontext_t, getcontext, setcontext, makecontext, swapcontext};
-use self::sys::{valloc, free};
-
-// `Stack` is a page-aligned region of memory suitable for use as a coroutine's stack.
-//
-// It's allocated using C `valloc()` and dropped using C `free()`.
-struct Stack {
-    size: usize,
-    ptr: *mut c_void,
+use std::ptr;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::sys::{ucontext_t, getcontext, setcontext, makecontext, swapcontext};
+use crate::stack::Stack;
+use crate::pool::global_pool;
+
+/// The outcome of yielding into a coroutine: either it yielded a value back out and is still
+/// running, or it ran to completion and produced its final value.
+pub enum CoroutineResult<Yield, Return> {
+    /// The coroutine called `Coroutine::yield_back` with this value, and remains suspended.
+    Yielded(Yield),
+
+    /// The coroutine's body returned this value, and it is now terminated.
+    Complete(Return),
 }
 
-impl Stack {
-    fn new(size: usize) -> Stack {
-        let ptr = unsafe { valloc(size) };
+/// A value crossing a single context switch: a `Resume` going in, or a `Yield`/`Return` coming
+/// back out.
+enum Transfer<Resume, Yield, Return> {
+    Resume(Resume),
+    Yield(Yield),
+    Return(Return),
 
-        if ptr as usize == 0 {
-            panic!("valloc() failed");
-        }
-
-        Stack{ size: size, ptr: ptr }
-    }
-}
-
-impl Drop for Stack {
-    fn drop(&mut self) {
-        unsafe {
-            free(self.ptr);
-        }
-    }
+    /// The coroutine's body panicked; this carries the payload to be re-raised in the caller.
+    Panic(Box<dyn Any + Send>),
 }
 
 /// A `Handle` is created for the outside of a coroutine. It contains the coroutine's thread state
 /// and the coroutine's stack.
 ///
-/// # Safety
-///
-/// It's probably not a good idea to drop the `Handle` while the coroutine is running.
-pub struct Handle<'f> {
+/// Dropping a `Handle` while its coroutine is suspended — whether mid-body, or having never run
+/// the body at all yet — forces the coroutine to unwind first, so that any locals the body left
+/// live across a `yield_back`, or the closure itself if it never even started, still run their
+/// destructors.
+pub struct Handle<'f, Resume, Yield, Return> {
     ctx: ucontext_t,
 
-    #[allow(dead_code)]
-    stack: Stack, // Rust doesn't see the stack get used, but it is referenced by `ctx`.
+    // `None` only ever briefly, between `take()`ing it in `Drop` and the `Handle` itself going
+    // away; Rust doesn't see the stack get used, but it is referenced by `ctx`.
+    stack: Option<Stack>,
+
+    link: Rc<Cell<Link<Resume, Yield, Return>>>,
 
-    link: Rc<Cell<Link>>,
     pd: PhantomData<&'f ()>
 }
 
 /// A `Coroutine` is created for the inside of a coroutine. It allows the coroutine to
-/// `yield_back()` control to its caller.
-pub struct Coroutine<'f> {
-    link: Rc<Cell<Link>>,
+/// `yield_back()` a value to its caller and receive the next `Resume` value in exchange.
+pub struct Coroutine<'f, Resume, Yield, Return> {
+    link: Rc<Cell<Link<Resume, Yield, Return>>>,
     pd: PhantomData<&'f ()>
 }
 
 /// `Link` encapsulates the communication of shared state between `Coroutine` and `Handle`.
-#[derive(Copy,Clone,PartialEq,Eq)]
-enum Link {
+enum Link<Resume, Yield, Return> {
     /// Indicates the coroutine is ready to be called
     Ready,
 
-    /// Indicates the coroutine _was_ called, and provides ucontext_t's to which it should return.
+    /// Indicates the coroutine _was_ called, provides ucontext_t's to which it should return, and
+    /// a slot through which the two sides exchange a `Transfer`.
     Called {
         left: *mut ucontext_t,
         right: *const ucontext_t,
+        transfer: *mut Option<Transfer<Resume, Yield, Return>>,
+    },
+
+    /// Indicates the `Handle` was dropped while the coroutine was suspended: the coroutine
+    /// should force itself to unwind the next time it reaches `yield_back()`, and provides the
+    /// ucontext_t's to which it should return once it has terminated.
+    Unwind {
+        left: *mut ucontext_t,
+        right: *const ucontext_t,
     },
 
     /// Indicates the coroutine is terminated and must not be called again.
     Terminated
 }
 
-impl<'f> Coroutine<'f> {
-    /// Create a new `Coroutine`+`Handle` with a default stack size.
+/// Private marker panic payload used to force a suspended coroutine to unwind. Nothing else
+/// should ever panic with this payload, so the entrypoint re-raises any payload that isn't
+/// exactly this marker rather than risk swallowing a real panic.
+struct ForcedUnwind;
+
+// `Link`'s fields are all raw pointers, so it's `Copy`/`Clone` regardless of `Resume`, `Yield`,
+// and `Return`; derive(Copy, Clone) would incorrectly demand those bounds on the type parameters.
+impl<Resume, Yield, Return> Copy for Link<Resume, Yield, Return> {}
+impl<Resume, Yield, Return> Clone for Link<Resume, Yield, Return> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<'f, Resume, Yield, Return> Coroutine<'f, Resume, Yield, Return> {
+    /// Create a new `Coroutine`+`Handle` with the process-global default stack size (see
+    /// `Config`).
     ///
     /// The coroutine will call `f(&mut Coroutine)` when it starts.
-    pub fn new<F>(f: F) -> Handle<'f>
-        where F: FnOnce(&mut Coroutine) + 'f
+    pub fn new<F>(f: F) -> Handle<'f, Resume, Yield, Return>
+        where F: FnOnce(&mut Coroutine<'f, Resume, Yield, Return>) -> Return + 'f
     {
-        Self::new_with_stack_size(f, 512*1024)
+        Self::new_with_stack_size(f, crate::pool::default_stack_size())
     }
 
     /// Create a new `Coroutine`+`Handle` with a specific stack size.
     ///
-    /// The coroutine will call `f(&mut Coroutine)` when it starts.
-    pub fn new_with_stack_size<F>(f: F, stack_size: usize) -> Handle<'f>
-        where F: FnOnce(&mut Coroutine) + 'f
+    /// The coroutine will call `f(&mut Coroutine)` when it starts. The stack is taken from the
+    /// process-global `StackPool` rather than freshly allocated when one of the right size is
+    /// available.
+    pub fn new_with_stack_size<F>(f: F, stack_size: usize) -> Handle<'f, Resume, Yield, Return>
+        where F: FnOnce(&mut Coroutine<'f, Resume, Yield, Return>) -> Return + 'f
     {
-        let stack = Stack::new(stack_size);
+        let stack = global_pool().get(stack_size);
 
         let mut ctx: ucontext_t = unsafe { mem::zeroed() };
 
         // prepare a link, which we'll share between the Handle and the Coroutine
-        let link: Rc<Cell<Link>> = Rc::new(Cell::new(Link::Ready));
+        let link: Rc<Cell<Link<Resume, Yield, Return>>> = Rc::new(Cell::new(Link::Ready));
 
         // prepare a Coroutine
         let coro = Coroutine {
@@ -106,7 +127,7 @@ impl<'f> Coroutine<'f> {
         };
 
         // wrap `f` and `coro` into Option<_>s
-        let mut coro: Option<Coroutine> = Some(coro);
+        let mut coro: Option<Coroutine<Resume, Yield, Return>> = Some(coro);
         let mut callback: Option<F> = Some(f);
 
         // define a polymorphic C entrypoint suitable for this <F>
@@ -118,33 +139,53 @@ impl<'f> Coroutine<'f> {
         //   - have the entrypoint move data into the coroutine stack
         //   - yield back
         //   - return from the constructor
-        unsafe extern "C" fn entrypoint<F>(coro: *mut Option<Coroutine>, f: *mut Option<F>)
-            where F: FnOnce(&mut Coroutine)
+        unsafe extern "C" fn entrypoint<F, Resume, Yield, Return>(
+            coro: *mut Option<Coroutine<Resume, Yield, Return>>,
+            f: *mut Option<F>,
+        )
+            where F: FnOnce(&mut Coroutine<Resume, Yield, Return>) -> Return
         {
             // take the constructor's Coroutine
-            let mut coro: Coroutine =
-                mem::transmute::<*mut Option<Coroutine>, &mut Option<Coroutine>>(coro)
+            let mut coro: Coroutine<Resume, Yield, Return> =
+                mem::transmute::<*mut Option<Coroutine<Resume, Yield, Return>>, &mut Option<Coroutine<Resume, Yield, Return>>>(coro)
                 .take()
                 .unwrap();
 
-            {
-                // take the constructor's function
-                let f: F =
-                    mem::transmute::<*mut Option<F>, &mut Option<F>>(f)
-                        .take()
-                        .unwrap();
-
-                // yield back, letting the constructor return
-                coro.yield_back();
-
-                // run the function
-                f(&mut coro);
-
-                // drop the function
+            // take the constructor's function
+            let f: F =
+                mem::transmute::<*mut Option<F>, &mut Option<F>>(f)
+                    .take()
+                    .unwrap();
+
+            // yield back, letting the constructor return. `bootstrap_yield_back` can itself
+            // panic with `ForcedUnwind` if the `Handle` is dropped before we're ever resumed
+            // again, so this also needs catching instead of letting it unwind straight through
+            // this `extern "C"` frame, which would be undefined behavior
+            match panic::catch_unwind(AssertUnwindSafe(|| coro.bootstrap_yield_back())) {
+                Ok(()) => {}
+                Err(ref payload) if payload.downcast_ref::<ForcedUnwind>().is_some() => {
+                    // the `Handle` was dropped before we ever ran `f`; we just unwound, so
+                    // there's no value to deliver back and no reason to call `f` at all.
+                    // `terminate_unwound` never returns, so drop `f` (and whatever it
+                    // captured) first instead of leaking it
+                    drop(f);
+                    coro.terminate_unwound();
+                    unreachable!();
+                }
+                Err(payload) => coro.terminate_panicked(payload),
             }
 
-            // terminate the coroutine
-            coro.terminate();
+            // run the function, catching a panic instead of letting it unwind straight through
+            // this `extern "C"` frame, which would be undefined behavior
+            match panic::catch_unwind(AssertUnwindSafe(|| f(&mut coro))) {
+                Ok(ret) => coro.terminate(ret),
+                Err(ref payload) if payload.downcast_ref::<ForcedUnwind>().is_some() => {
+                    // the `Handle` was dropped while we were suspended and asked us to unwind;
+                    // we just did, so there's no value to deliver back
+                    coro.terminate_unwound();
+                }
+                Err(payload) => coro.terminate_panicked(payload),
+            }
         }
 
         // express this plan in terms of <ucontext.h> API
@@ -160,7 +201,7 @@ impl<'f> Coroutine<'f> {
 
             // makecontext() takes a C function() and separately asks for args
             // this means we need to transmute into a no-arg function
-            let entrypoint: unsafe extern "C" fn(*mut Option<Coroutine>, *mut Option<F>) = entrypoint;
+            let entrypoint: unsafe extern "C" fn(*mut Option<Coroutine<Resume, Yield, Return>>, *mut Option<F>) = entrypoint;
             let entrypoint: unsafe extern "C" fn() = mem::transmute(entrypoint);
 
             // have the context run entrypoint(&mut coro, &mut f) when it starts
@@ -168,7 +209,7 @@ impl<'f> Coroutine<'f> {
                 &mut ctx as *mut ucontext_t,
                 Some(entrypoint),
                 2,
-                &mut coro as *mut Option<Coroutine>,
+                &mut coro as *mut Option<Coroutine<Resume, Yield, Return>>,
                 &mut callback as *mut Option<F>,
             );
         }
@@ -176,7 +217,7 @@ impl<'f> Coroutine<'f> {
         // assemble all the outer bits into the handle
         let mut handle = Handle {
             ctx,
-            stack,
+            stack: Some(stack),
             link,
             pd: PhantomData,
         };
@@ -185,7 +226,9 @@ impl<'f> Coroutine<'f> {
         // local `coro` and `f` Option<_>s.
         //
         // yield into the coroutine, let it take the values out of `coro` and `f`, and yield back.
-        handle.yield_in().unwrap();
+        // no `Resume` value exists yet, so this goes through the untyped bootstrap handshake
+        // rather than `yield_in()`.
+        handle.bootstrap();
 
         // the coroutine is now ready to invoke the user's function, and it shares no state except
         // `link`.
@@ -194,14 +237,16 @@ impl<'f> Coroutine<'f> {
         handle
     }
 
-    /// Yield control back to the caller. `Coroutine::yield_back()` will block until the caller
-    /// calls `Handle::yield_in()`.
-    pub fn yield_back(&mut self) {
+    /// Yield `value` back to the caller, blocking until the caller calls `Handle::yield_in()`
+    /// again, and returning the `Resume` value it passed.
+    pub fn yield_back(&mut self, value: Yield) -> Resume {
         let link = Cell::new(Link::Ready);
         self.link.swap(&link);
         match link.into_inner() {
-            Link::Called { left, right } => {
+            Link::Called { left, right, transfer } => {
                 unsafe {
+                    *transfer = Some(Transfer::Yield(value));
+
                     if swapcontext(left, right) != 0 {
                         // failed
                         panic!("swapcontext failed");
@@ -212,29 +257,114 @@ impl<'f> Coroutine<'f> {
                 panic!("don't know where to yield back to");
             }
         }
+
+        // we've been resumed: whoever called `Handle::yield_in()` left our `Resume` value in the
+        // slot named by the `Link::Called` they just installed
+        match self.link.get() {
+            Link::Called { transfer, .. } => {
+                match unsafe { (*transfer).take() } {
+                    Some(Transfer::Resume(value)) => value,
+                    _ => panic!("coroutine resumed with no resume value"),
+                }
+            }
+            Link::Unwind { .. } => {
+                // the `Handle` was dropped while we were suspended here; force ourselves to
+                // unwind so that drop glue runs for everything live in this stack and above.
+                // `catch_unwind` in the entrypoint recognizes and swallows this exact payload.
+                panic::resume_unwind(Box::new(ForcedUnwind))
+            }
+            _ => panic!("coroutine resumed with nowhere to read from"),
+        }
     }
 
-    /// Terminate the coroutine.
-    ///
-    /// # Safety
+    /// Like `yield_back()`, but used only for the untyped handshake that lets the constructor
+    /// regain control once the entrypoint has taken ownership of its locals. No `Transfer` value
+    /// crosses this particular switch.
     ///
-    /// Never returns.
-    unsafe fn terminate(&mut self) {
-        let link = Cell::new(Link::Terminated);
+    /// A `Handle` can be dropped before ever calling `yield_in()`, while we're suspended right
+    /// here; like `yield_back()`, we check for `Link::Unwind` on resume and force an unwind in
+    /// that case, so the closure (and anything it captured) still gets dropped instead of leaked.
+    fn bootstrap_yield_back(&mut self) {
+        let link = Cell::new(Link::Ready);
         self.link.swap(&link);
         match link.into_inner() {
-            Link::Called { left: _, right } => {
-                setcontext(right);
-                panic!("setcontext() failed");
+            Link::Called { left, right, .. } => {
+                unsafe {
+                    if swapcontext(left, right) != 0 {
+                        // failed
+                        panic!("swapcontext failed");
+                    }
+                }
             }
             _ => {
-                panic!("coroutine is complete but cannot return to caller");
+                panic!("don't know where to yield back to");
             }
         }
+
+        if let Link::Unwind { .. } = self.link.get() {
+            // `catch_unwind` around this call in the entrypoint recognizes and swallows this
+            // exact payload
+            panic::resume_unwind(Box::new(ForcedUnwind));
+        }
+    }
+
+    /// Terminate the coroutine, delivering `value` to whoever yields in next.
+    ///
+    /// # Safety
+    ///
+    /// Never returns.
+    unsafe fn terminate(&mut self, value: Return) {
+        let (transfer, right) = match self.link.get() {
+            Link::Called { transfer, right, .. } => (transfer, right),
+            _ => panic!("coroutine is complete but cannot return to caller"),
+        };
+
+        *transfer = Some(Transfer::Return(value));
+        self.link.set(Link::Terminated);
+
+        setcontext(right);
+        panic!("setcontext() failed");
+    }
+
+    /// Terminate the coroutine because its body panicked, delivering the panic payload to
+    /// whoever yields in next so they can resume the unwind in their own stack.
+    ///
+    /// # Safety
+    ///
+    /// Never returns.
+    unsafe fn terminate_panicked(&mut self, payload: Box<dyn Any + Send>) {
+        let (transfer, right) = match self.link.get() {
+            Link::Called { transfer, right, .. } => (transfer, right),
+            _ => panic!("coroutine is complete but cannot return to caller"),
+        };
+
+        *transfer = Some(Transfer::Panic(payload));
+        self.link.set(Link::Terminated);
+
+        setcontext(right);
+        panic!("setcontext() failed");
+    }
+
+    /// Terminate the coroutine after a forced unwind requested by `Handle::drop`. There's no
+    /// value to deliver: the `Handle` side isn't reading one, just waiting for termination.
+    ///
+    /// # Safety
+    ///
+    /// Never returns.
+    unsafe fn terminate_unwound(&mut self) {
+        let right = match self.link.get() {
+            Link::Unwind { right, .. } => right,
+            _ => panic!("coroutine is complete but cannot return to caller"),
+        };
+
+        self.link.set(Link::Terminated);
+
+        setcontext(right);
+        panic!("setcontext() failed");
     }
 }
 
-impl<'f> Handle<'f> {
+impl<'f, Resume, Yield, Return> Handle<'f, Resume, Yield, Return> {
     /// Indicates whether or not the coroutine has terminated.
     pub fn is_terminated(&self) -> bool {
         match self.link.get() {
@@ -243,22 +373,31 @@ impl<'f> Handle<'f> {
         }
     }
 
-    /// Yield control into the coroutine. This function blocks until either the coroutine calls
-    /// `Coroutine::yield_back()` or returns.
+    /// Yield control into the coroutine, delivering `value`. This function blocks until either
+    /// the coroutine calls `Coroutine::yield_back()` or returns.
+    ///
+    /// The body receives `Resume` values only as the return of `yield_back()`, so the `value`
+    /// passed to the first call, which starts the body, is dropped without the body seeing it.
+    ///
+    /// Returns `Ok(CoroutineResult::Yielded(_))` or `Ok(CoroutineResult::Complete(_))` on success,
+    /// or `Err(())` if the coroutine could not be called because it has already terminated.
     ///
-    /// Returns `Ok(still_running: bool)` on success, or `Err(())` if the coroutine could not be
-    /// called because it has already terminated.
-    pub fn yield_in(&mut self) -> Result<bool, ()> {
+    /// If the coroutine's body panics, that panic is resumed here instead of returning, as if
+    /// the body had been invoked inline in this stack frame.
+    pub fn yield_in(&mut self, value: Resume) -> Result<CoroutineResult<Yield, Return>, ()> {
         if self.is_terminated() {
             return Err(());
         }
 
+        let mut transfer: Option<Transfer<Resume, Yield, Return>> = Some(Transfer::Resume(value));
+
         unsafe {
             // set the link to come back here
             let here: ucontext_t = mem::uninitialized();
             self.link.set(Link::Called{
                 left: &self.ctx as *const ucontext_t as _,
                 right: &here as *const ucontext_t as _,
+                transfer: &mut transfer,
             });
 
             // swap in
@@ -268,14 +407,74 @@ impl<'f> Handle<'f> {
             }
         }
 
+        // the coroutine wrote into our `transfer` slot before switching back
         if self.is_terminated() {
-            // terminated
-            // TODO: free stack early?
-            Ok(false)
-
+            match transfer.take() {
+                Some(Transfer::Return(value)) => Ok(CoroutineResult::Complete(value)),
+                // re-raise the coroutine's panic here, in the caller's stack, as if the body had
+                // run inline
+                Some(Transfer::Panic(payload)) => panic::resume_unwind(payload),
+                _ => panic!("coroutine terminated with no return value"),
+            }
         } else {
-            // still running
-            Ok(true)
+            match transfer.take() {
+                Some(Transfer::Yield(value)) => Ok(CoroutineResult::Yielded(value)),
+                _ => panic!("coroutine yielded with no value"),
+            }
+        }
+    }
+
+    /// Like `yield_in()`, but used only for the untyped handshake performed once by
+    /// `Coroutine::new_with_stack_size()`. No `Transfer` value crosses this particular switch.
+    fn bootstrap(&mut self) {
+        unsafe {
+            let here: ucontext_t = mem::uninitialized();
+            self.link.set(Link::Called{
+                left: &self.ctx as *const ucontext_t as _,
+                right: &here as *const ucontext_t as _,
+                transfer: ptr::null_mut(),
+            });
+
+            if swapcontext(&here as *const ucontext_t as *mut ucontext_t, &self.ctx as *const ucontext_t) != 0 {
+                panic!("swapcontext failed");
+            }
+        }
+    }
+
+    /// Force a suspended coroutine to unwind its stack, running drop glue for every local the
+    /// body left live across a `yield_back`, then block until it reaches `Link::Terminated`.
+    fn force_unwind(&mut self) {
+        unsafe {
+            let here: ucontext_t = mem::uninitialized();
+            self.link.set(Link::Unwind {
+                left: &self.ctx as *const ucontext_t as _,
+                right: &here as *const ucontext_t as _,
+            });
+
+            if swapcontext(&here as *const ucontext_t as *mut ucontext_t, &self.ctx as *const ucontext_t) != 0 {
+                panic!("swapcontext failed");
+            }
+        }
+
+        debug_assert!(self.is_terminated(), "coroutine did not terminate after a forced unwind");
+    }
+}
+
+impl<'f, Resume, Yield, Return> Drop for Handle<'f, Resume, Yield, Return> {
+    fn drop(&mut self) {
+        if !self.is_terminated() {
+            // the coroutine is suspended somewhere between having been handed its closure (at
+            // the latest, inside `bootstrap_yield_back`) and returning, with locals potentially
+            // live across its last `yield_back`; force it to unwind so their destructors run
+            // instead of leaking them
+            self.force_unwind();
+        }
+
+        // only a terminated coroutine's stack holds no live state, so only it is safe to recycle
+        if self.is_terminated() {
+            if let Some(stack) = self.stack.take() {
+                global_pool().put(stack);
+            }
         }
     }
 }
@@ -291,13 +490,13 @@ mod test {
 
         {
             // create a coroutine
-            let mut coro = Coroutine::new(|_: &mut Coroutine| {
+            let mut coro = Coroutine::new(|_: &mut Coroutine<(), (), ()>| {
                 seq.store(1, Ordering::Release);
             });
 
             // don't ever actually call it
             if false {
-                coro.yield_in().unwrap();
+                coro.yield_in(()).unwrap();
             }
 
             // dropping without calling should not be an error
@@ -311,12 +510,12 @@ mod test {
     fn test_ucontext() {
         let seq = AtomicUsize::new(0);
 
-        let mut coro = Coroutine::new(|coro: &mut Coroutine| {
+        let mut coro = Coroutine::new(|coro: &mut Coroutine<(), (), ()>| {
             // in coroutine (1 => 2)
             assert_eq!(seq.load(Ordering::Acquire), 1);
             seq.store(2, Ordering::Release);
 
-            coro.yield_back();
+            coro.yield_back(());
 
             // back in coroutine (3 => 4)
             assert_eq!(seq.load(Ordering::Acquire), 3);
@@ -330,17 +529,129 @@ mod test {
         assert_eq!(coro.is_terminated(), false);
         seq.store(1, Ordering::Release);
 
-        coro.yield_in().unwrap();
+        coro.yield_in(()).unwrap();
 
         // back from coroutine (2 => 3)
         assert_eq!(seq.load(Ordering::Acquire), 2);
         assert_eq!(coro.is_terminated(), false);
         seq.store(3, Ordering::Release);
 
-        coro.yield_in().unwrap();
+        coro.yield_in(()).unwrap();
 
         // done (4!)
         assert_eq!(seq.load(Ordering::Acquire), 4);
         assert_eq!(coro.is_terminated(), true);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_typed_values() {
+        let mut coro = Coroutine::new(|coro: &mut Coroutine<i32, &'static str, bool>| {
+            let a = coro.yield_back("first");
+            let b = coro.yield_back("second");
+            a + b == 3
+        });
+
+        // the first `yield_in` only starts the body, which has nowhere to receive its value;
+        // each `yield_back` returns the value passed to the `yield_in` after it
+        match coro.yield_in(0).unwrap() {
+            CoroutineResult::Yielded(y) => assert_eq!(y, "first"),
+            CoroutineResult::Complete(_) => panic!("expected a yield"),
+        }
+
+        match coro.yield_in(1).unwrap() {
+            CoroutineResult::Yielded(y) => assert_eq!(y, "second"),
+            CoroutineResult::Complete(_) => panic!("expected a yield"),
+        }
+
+        match coro.yield_in(2).unwrap() {
+            CoroutineResult::Yielded(_) => panic!("expected completion"),
+            CoroutineResult::Complete(r) => assert!(r),
+        }
+
+        assert!(coro.is_terminated());
+    }
+
+    #[test]
+    fn test_panic_propagates() {
+        let mut coro = Coroutine::new(|_: &mut Coroutine<(), (), ()>| -> () {
+            panic!("boom");
+        });
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| coro.yield_in(())));
+        assert!(result.is_err());
+        assert!(coro.is_terminated());
+    }
+
+    #[test]
+    fn test_stack_reuse() {
+        // a distinctive size so this test doesn't fight other tests over pooled stacks
+        let stack_size = 123 * 4096;
+
+        for _ in 0..4 {
+            let mut coro = Coroutine::new_with_stack_size(
+                |_: &mut Coroutine<(), (), ()>| {},
+                stack_size,
+            );
+            coro.yield_in(()).unwrap();
+            assert!(coro.is_terminated());
+            // dropping here returns the stack to the pool for the next iteration to reuse
+        }
+    }
+
+    #[test]
+    fn test_drop_unwinds_suspended_coroutine() {
+        struct DropFlag<'a>(&'a AtomicUsize);
+        impl<'a> Drop for DropFlag<'a> {
+            fn drop(&mut self) {
+                self.0.store(1, Ordering::Release);
+            }
+        }
+
+        let dropped = AtomicUsize::new(0);
+
+        {
+            let mut coro = Coroutine::new(|coro: &mut Coroutine<(), (), ()>| {
+                let _guard = DropFlag(&dropped);
+                coro.yield_back(());
+                unreachable!("forced unwind should never resume the body");
+            });
+
+            coro.yield_in(()).unwrap();
+            assert!(!coro.is_terminated());
+            assert_eq!(dropped.load(Ordering::Acquire), 0);
+
+            // dropping while suspended mid-body should run `DropFlag`'s destructor
+        }
+
+        assert_eq!(dropped.load(Ordering::Acquire), 1);
+    }
+
+    #[test]
+    fn test_drop_unwinds_never_started_coroutine() {
+        struct DropFlag<'a>(&'a AtomicUsize);
+        impl<'a> Drop for DropFlag<'a> {
+            fn drop(&mut self) {
+                self.0.store(1, Ordering::Release);
+            }
+        }
+
+        let dropped = AtomicUsize::new(0);
+
+        {
+            let guard = DropFlag(&dropped);
+
+            let coro = Coroutine::new(move |_: &mut Coroutine<(), (), ()>| {
+                let _guard = guard;
+                unreachable!("forced unwind should never resume the body");
+            });
+
+            assert!(!coro.is_terminated());
+            assert_eq!(dropped.load(Ordering::Acquire), 0);
+
+            // dropping here, without ever calling `yield_in`, should still unwind the closure
+            // the coroutine has already been handed, running `DropFlag`'s destructor
+        }
+
+        assert_eq!(dropped.load(Ordering::Acquire), 1);
+    }
+}